@@ -0,0 +1,275 @@
+use crate::highlight::Highlighter;
+use crate::preprocess::preprocess;
+use crate::{create_markdown_parser, links, render_template, search, to_html, to_plain_text, Json};
+use anyhow::{Context, Result};
+use pulldown_cmark::{Event::*, LinkType, Tag::*, TagEnd};
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// One entry of a book's chapter tree, as parsed from `SUMMARY.md`.
+struct Chapter {
+    title: String,
+    file: String,
+    children: Vec<Chapter>,
+}
+
+/// A chapter entry as exposed to templates for the navigation sidebar.
+#[derive(Clone, Serialize)]
+pub(crate) struct NavEntry {
+    title: String,
+    href: String,
+    children: Vec<NavEntry>,
+}
+
+/// A link to the previous or next chapter in reading order.
+#[derive(Serialize)]
+pub(crate) struct NavLink {
+    title: String,
+    href: String,
+}
+
+/// Extra tera context for a single page of a rendered book.
+pub(crate) struct BookNav {
+    pub(crate) path: String,
+    pub(crate) prev: Option<NavLink>,
+    pub(crate) next: Option<NavLink>,
+    pub(crate) chapters: Vec<NavEntry>,
+}
+
+pub(crate) fn render_book(
+    book_dir: &str,
+    output_dir: Option<String>,
+    metadata: Option<Json>,
+    template_files: &[String],
+    highlighter: &Highlighter,
+    search_index: Option<String>,
+    link_map_file: Option<String>,
+) -> Result<()> {
+    let book_dir = Path::new(book_dir);
+    let summary_path = book_dir.join("SUMMARY.md");
+    let summary = fs::read_to_string(&summary_path)
+        .with_context(|| format!("failed to read {}", summary_path.display()))?;
+    let chapters = parse_summary(&summary);
+    let nav_tree = to_nav_tree(&chapters);
+    let flattened = flatten(&chapters);
+
+    let output_dir = output_dir.map(PathBuf::from).unwrap_or_else(|| book_dir.to_owned());
+    fs::create_dir_all(&output_dir)?;
+    let mut indexed_documents = Vec::new();
+    let base_link_map = links::LinkMap::from_file(link_map_file.as_deref())?;
+
+    for (index, chapter) in flattened.iter().enumerate() {
+        let chapter_path = book_dir.join(&chapter.file);
+        let markdown = fs::read_to_string(&chapter_path)
+            .with_context(|| format!("failed to read chapter {}", chapter_path.display()))?;
+        let link_map = base_link_map.with_frontmatter(&markdown)?;
+        let mut resolve_link = |broken_link| link_map.resolve(broken_link);
+        let preprocessed = preprocess(
+            create_markdown_parser(&markdown, &mut resolve_link),
+            highlighter,
+        )?;
+        let title = preprocessed
+            .title_events
+            .as_ref()
+            .map(|events| to_plain_text(events.iter().cloned()))
+            .unwrap_or_else(|| chapter.title.clone());
+        let body_html = to_html(preprocessed.events.into_iter());
+
+        let book_nav = BookNav {
+            path: html_file_name(&chapter.file),
+            prev: index
+                .checked_sub(1)
+                .and_then(|i| flattened.get(i).copied())
+                .map(nav_link),
+            next: flattened.get(index + 1).copied().map(nav_link),
+            chapters: nav_tree.clone(),
+        };
+
+        let html = render_template(
+            Some(&title),
+            metadata.clone(),
+            preprocessed.has_math,
+            preprocessed.has_highlighted_code,
+            &preprocessed.metadata,
+            &preprocessed.toc,
+            &body_html,
+            template_files,
+            Some(&book_nav),
+        )?
+        .unwrap_or(body_html);
+
+        let out_path = output_dir.join(&book_nav.path);
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(out_path, html)?;
+        indexed_documents.push((book_nav.path, preprocessed.sections));
+    }
+
+    if let Some(search_index) = search_index {
+        search::write_index(&search_index, &indexed_documents)?;
+    }
+
+    Ok(())
+}
+
+fn nav_link(chapter: &Chapter) -> NavLink {
+    NavLink {
+        title: chapter.title.clone(),
+        href: html_file_name(&chapter.file),
+    }
+}
+
+fn html_file_name(chapter_file: &str) -> String {
+    Path::new(chapter_file)
+        .with_extension("html")
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn to_nav_tree(chapters: &[Chapter]) -> Vec<NavEntry> {
+    chapters
+        .iter()
+        .map(|c| NavEntry {
+            title: c.title.clone(),
+            href: html_file_name(&c.file),
+            children: to_nav_tree(&c.children),
+        })
+        .collect()
+}
+
+fn flatten(chapters: &[Chapter]) -> Vec<&Chapter> {
+    let mut flattened = Vec::new();
+    fn walk<'a>(chapters: &'a [Chapter], flattened: &mut Vec<&'a Chapter>) {
+        for chapter in chapters {
+            flattened.push(chapter);
+            walk(&chapter.children, flattened);
+        }
+    }
+    walk(chapters, &mut flattened);
+    flattened
+}
+
+/// Parses a `SUMMARY.md`-style nested markdown list of chapter links into a
+/// tree: each list item's link target is a chapter file, and list nesting
+/// denotes section depth.
+fn parse_summary(markdown: &str) -> Vec<Chapter> {
+    let mut list_stack: Vec<Vec<Chapter>> = vec![Vec::new()];
+    let mut item_stack: Vec<Option<Chapter>> = Vec::new();
+    let mut current_link: Option<(String, String)> = None;
+
+    for event in pulldown_cmark::Parser::new(markdown) {
+        match event {
+            Start(List(_)) => list_stack.push(Vec::new()),
+            End(TagEnd::List(_)) => {
+                let children = list_stack.pop().unwrap_or_default();
+                match item_stack.last_mut() {
+                    Some(Some(parent)) => parent.children = children,
+                    _ => list_stack.last_mut().unwrap().extend(children),
+                }
+            }
+            Start(Item) => item_stack.push(None),
+            End(TagEnd::Item) => {
+                if let Some(Some(chapter)) = item_stack.pop() {
+                    list_stack.last_mut().unwrap().push(chapter);
+                }
+            }
+            Start(Link {
+                link_type: LinkType::Inline | LinkType::Reference | LinkType::Shortcut,
+                dest_url,
+                ..
+            }) => current_link = Some((dest_url.into_string(), String::new())),
+            End(TagEnd::Link) => {
+                if let (Some((dest, title)), Some(slot)) =
+                    (current_link.take(), item_stack.last_mut())
+                {
+                    *slot = Some(Chapter {
+                        title,
+                        file: dest,
+                        children: Vec::new(),
+                    });
+                }
+            }
+            Text(text) => {
+                if let Some((_, title)) = &mut current_link {
+                    title.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    list_stack.pop().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles_and_files(chapters: &[Chapter]) -> Vec<(&str, &str)> {
+        chapters.iter().map(|c| (c.title.as_str(), c.file.as_str())).collect()
+    }
+
+    #[test]
+    fn parses_a_flat_list() {
+        let chapters = parse_summary(
+            "\
+- [Introduction](intro.md)
+- [Getting Started](getting-started.md)
+",
+        );
+        assert_eq!(
+            titles_and_files(&chapters),
+            vec![("Introduction", "intro.md"), ("Getting Started", "getting-started.md")]
+        );
+        assert!(chapters.iter().all(|c| c.children.is_empty()));
+    }
+
+    #[test]
+    fn nests_sub_items_under_their_parent() {
+        let chapters = parse_summary(
+            "\
+- [Guide](guide.md)
+  - [Installing](install.md)
+  - [Configuring](configure.md)
+- [Reference](reference.md)
+",
+        );
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].title, "Guide");
+        assert_eq!(
+            titles_and_files(&chapters[0].children),
+            vec![("Installing", "install.md"), ("Configuring", "configure.md")]
+        );
+        assert_eq!(chapters[1].title, "Reference");
+        assert!(chapters[1].children.is_empty());
+    }
+
+    #[test]
+    fn nests_multiple_levels_deep() {
+        let chapters = parse_summary(
+            "\
+- [Part One](part1.md)
+  - [Chapter One](part1/ch1.md)
+    - [Section One](part1/ch1/s1.md)
+  - [Chapter Two](part1/ch2.md)
+",
+        );
+        assert_eq!(chapters.len(), 1);
+        let part_one = &chapters[0];
+        assert_eq!(part_one.children.len(), 2);
+        let chapter_one = &part_one.children[0];
+        assert_eq!(chapter_one.title, "Chapter One");
+        assert_eq!(chapter_one.children.len(), 1);
+        assert_eq!(chapter_one.children[0].title, "Section One");
+        assert_eq!(part_one.children[1].title, "Chapter Two");
+        assert!(part_one.children[1].children.is_empty());
+    }
+
+    #[test]
+    fn html_file_name_preserves_subdirectories() {
+        assert_eq!(html_file_name("part1/ch1.md"), "part1/ch1.html");
+        assert_eq!(html_file_name("intro.md"), "intro.html");
+    }
+}
@@ -2,8 +2,53 @@ use anyhow::{anyhow, Context as _, Result};
 use pulldown_cmark::CowStr;
 use std::io::Write as _;
 use std::process::{Command, Stdio};
+use syntect::highlighting::{Theme, ThemeSet};
+use syntect::html::highlighted_html_for_string;
+use syntect::parsing::SyntaxSet;
 
-pub(crate) fn highlight_code(command: &str, language: CowStr<'_>, code: String) -> Result<String> {
+/// How fenced code blocks get turned into highlighted HTML.
+pub(crate) enum Highlighter {
+    /// Code blocks pass through unhighlighted.
+    None,
+    /// Shell out to an external command per block, e.g. `pygmentize`/`chroma`.
+    Command(String),
+    /// Highlight in-process with syntect instead of spawning a subprocess.
+    Builtin {
+        syntax_set: SyntaxSet,
+        theme: Theme,
+    },
+}
+
+impl Highlighter {
+    pub(crate) fn builtin(theme_name: &str) -> Result<Highlighter> {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        let theme = ThemeSet::load_defaults()
+            .themes
+            .get(theme_name)
+            .with_context(|| format!("unknown highlight theme '{theme_name}'"))?
+            .clone();
+        Ok(Highlighter::Builtin { syntax_set, theme })
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        !matches!(self, Highlighter::None)
+    }
+
+    pub(crate) fn highlight(&self, language: CowStr<'_>, code: String) -> Result<String> {
+        match self {
+            Highlighter::None => unreachable!("fenced code blocks aren't intercepted when disabled"),
+            Highlighter::Command(command) => highlight_with_command(command, language, code),
+            Highlighter::Builtin { syntax_set, theme } => {
+                let syntax = syntax_set
+                    .find_syntax_by_token(&language)
+                    .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+                Ok(highlighted_html_for_string(&code, syntax_set, syntax, theme)?)
+            }
+        }
+    }
+}
+
+fn highlight_with_command(command: &str, language: CowStr<'_>, code: String) -> Result<String> {
     let mut words: Vec<_> = shell_words::split(command)?;
     replace_language_placeholder(&mut words, language);
 
@@ -33,3 +78,46 @@ fn replace_language_placeholder(words: &mut Vec<String>, language: CowStr<'_>) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_language_placeholder_substitutes_every_occurrence() {
+        let mut words = vec![
+            "highlight".to_owned(),
+            "--syntax".to_owned(),
+            "{}".to_owned(),
+            "--name".to_owned(),
+            "{}".to_owned(),
+        ];
+        replace_language_placeholder(&mut words, "rust".into());
+        assert_eq!(words, vec!["highlight", "--syntax", "rust", "--name", "rust"]);
+    }
+
+    #[test]
+    fn replace_language_placeholder_leaves_other_words_untouched() {
+        let mut words = vec!["chroma".to_owned(), "--lexer={}".to_owned()];
+        replace_language_placeholder(&mut words, "go".into());
+        assert_eq!(words, vec!["chroma", "--lexer={}"]);
+    }
+
+    #[test]
+    fn builtin_highlighter_is_enabled() {
+        let highlighter = Highlighter::builtin("InspiredGitHub").unwrap();
+        assert!(highlighter.is_enabled());
+        let html = highlighter.highlight("rust".into(), "fn main() {}".to_owned()).unwrap();
+        assert!(html.contains("fn"));
+    }
+
+    #[test]
+    fn builtin_highlighter_rejects_unknown_theme() {
+        assert!(Highlighter::builtin("not-a-real-theme").is_err());
+    }
+
+    #[test]
+    fn none_highlighter_is_disabled() {
+        assert!(!Highlighter::None.is_enabled());
+    }
+}
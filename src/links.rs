@@ -0,0 +1,179 @@
+use anyhow::{Context, Result};
+use pulldown_cmark::{BrokenLink, CowStr};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum LinkTarget {
+    Url(String),
+    WithTitle {
+        url: String,
+        #[serde(default)]
+        title: String,
+    },
+}
+
+impl LinkTarget {
+    fn into_url_and_title(self) -> (String, String) {
+        match self {
+            LinkTarget::Url(url) => (url, String::new()),
+            LinkTarget::WithTitle { url, title } => (url, title),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct Frontmatter {
+    #[serde(default)]
+    links: HashMap<String, LinkTarget>,
+}
+
+/// A case-insensitive table of shortcut/reference link names to their
+/// `(url, title)`. Built from a `--link-map` JSON file merged with a
+/// document's own `links` frontmatter table, which takes precedence so a
+/// single chapter can override or extend the shared map.
+pub(crate) struct LinkMap(HashMap<String, (String, String)>);
+
+impl LinkMap {
+    /// Parses the `--link-map` JSON file, if any, into the base map shared
+    /// across every chapter of a book. Kept separate from
+    /// [`LinkMap::with_frontmatter`] so a book render only reads and parses
+    /// this file once instead of once per chapter.
+    pub(crate) fn from_file(link_map_file: Option<&str>) -> Result<LinkMap> {
+        let mut links = HashMap::new();
+
+        if let Some(path) = link_map_file {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("failed to read link map '{path}'"))?;
+            let entries: HashMap<String, LinkTarget> =
+                json::from_str(&content).context("failed to parse link map as JSON")?;
+            insert_all(&mut links, entries);
+        }
+
+        Ok(LinkMap(links))
+    }
+
+    pub(crate) fn build(link_map_file: Option<&str>, markdown: &str) -> Result<LinkMap> {
+        Self::from_file(link_map_file)?.with_frontmatter(markdown)
+    }
+
+    /// Merges `markdown`'s own `links` frontmatter table on top of this map,
+    /// returning the combined map. The frontmatter entries take precedence
+    /// over anything already present.
+    pub(crate) fn with_frontmatter(&self, markdown: &str) -> Result<LinkMap> {
+        let mut links = self.0.clone();
+
+        if let Some(frontmatter) = leading_frontmatter(markdown) {
+            let frontmatter: Frontmatter =
+                toml::from_str(frontmatter).context("failed to parse frontmatter `links` table")?;
+            insert_all(&mut links, frontmatter.links);
+        }
+
+        Ok(LinkMap(links))
+    }
+
+    pub(crate) fn resolve<'a>(&self, link: BrokenLink<'a>) -> Option<(CowStr<'a>, CowStr<'a>)> {
+        let (url, title) = self.0.get(&link.reference.to_lowercase())?;
+        Some((url.clone().into(), title.clone().into()))
+    }
+}
+
+fn insert_all(links: &mut HashMap<String, (String, String)>, entries: HashMap<String, LinkTarget>) {
+    for (key, target) in entries {
+        links.insert(key.to_lowercase(), target.into_url_and_title());
+    }
+}
+
+/// Pulls out the raw TOML of a leading `+++`-delimited frontmatter block
+/// without running the full markdown parser, since the link map has to be
+/// known before the parser (and its broken-link callback) can be built.
+fn leading_frontmatter(markdown: &str) -> Option<&str> {
+    let body = markdown.strip_prefix("+++")?;
+    let body = body.strip_prefix('\n').unwrap_or(body);
+    let end = body.find("\n+++")?;
+    Some(&body[..end])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve<'a>(map: &LinkMap, reference: &'a str) -> Option<(String, String)> {
+        let link = BrokenLink {
+            span: 0..0,
+            link_type: pulldown_cmark::LinkType::Shortcut,
+            reference: reference.into(),
+        };
+        map.resolve(link).map(|(url, title)| (url.into_string(), title.into_string()))
+    }
+
+    fn build_map(link_map_json: &str, markdown: &str) -> LinkMap {
+        // Tests run in parallel threads of one process, so each call needs its own directory.
+        static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("seite-links-test-{}-{n}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("link-map.json");
+        std::fs::write(&path, link_map_json).unwrap();
+        let map = LinkMap::build(Some(path.to_str().unwrap()), markdown).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        map
+    }
+
+    #[test]
+    fn resolves_from_the_link_map_file() {
+        let map = build_map(r#"{"rust": "https://rust-lang.org"}"#, "");
+        assert_eq!(resolve(&map, "rust"), Some(("https://rust-lang.org".to_owned(), String::new())));
+    }
+
+    #[test]
+    fn resolution_is_case_insensitive() {
+        let map = build_map(r#"{"Rust": "https://rust-lang.org"}"#, "");
+        assert_eq!(resolve(&map, "RUST").unwrap().0, "https://rust-lang.org");
+    }
+
+    #[test]
+    fn accepts_a_url_and_title_form() {
+        let map = build_map(r#"{"rust": {"url": "https://rust-lang.org", "title": "Rust"}}"#, "");
+        assert_eq!(
+            resolve(&map, "rust"),
+            Some(("https://rust-lang.org".to_owned(), "Rust".to_owned()))
+        );
+    }
+
+    #[test]
+    fn frontmatter_links_take_precedence_over_the_link_map_file() {
+        let markdown = "+++\n[links]\nrust = \"https://doc.rust-lang.org\"\n+++\nbody";
+        let map = build_map(r#"{"rust": "https://rust-lang.org"}"#, markdown);
+        assert_eq!(resolve(&map, "rust").unwrap().0, "https://doc.rust-lang.org");
+    }
+
+    #[test]
+    fn unknown_references_do_not_resolve() {
+        let map = build_map(r#"{"rust": "https://rust-lang.org"}"#, "");
+        assert_eq!(resolve(&map, "nope"), None);
+    }
+
+    #[test]
+    fn with_frontmatter_can_be_applied_to_several_chapters_from_one_base_map() {
+        let dir = std::env::temp_dir().join(format!("seite-links-test-base-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("link-map.json");
+        std::fs::write(&path, r#"{"rust": "https://rust-lang.org"}"#).unwrap();
+        let base = LinkMap::from_file(Some(path.to_str().unwrap())).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let chapter_one = base.with_frontmatter("").unwrap();
+        assert_eq!(resolve(&chapter_one, "rust").unwrap().0, "https://rust-lang.org");
+
+        let chapter_two = base
+            .with_frontmatter("+++\n[links]\nrust = \"https://doc.rust-lang.org\"\n+++\n")
+            .unwrap();
+        assert_eq!(resolve(&chapter_two, "rust").unwrap().0, "https://doc.rust-lang.org");
+
+        // The base map itself is untouched by chapter_two's override.
+        assert_eq!(resolve(&base, "rust").unwrap().0, "https://rust-lang.org");
+    }
+}
@@ -2,25 +2,36 @@ use anyhow::Result;
 use clap::Parser;
 use itertools::Itertools as _;
 use preprocess::preprocess;
+use preprocess::TocEntry;
 use std::io::Read as _;
 use std::path::Path;
 use std::str::FromStr;
 use std::{fs, io};
 
+mod book;
 mod highlight;
+mod links;
 mod preprocess;
+mod search;
 
 #[derive(Debug, Parser)]
 struct Args {
     /// Markdown file to render to HTML. Use `-` to read from stdin.
-    #[arg(value_parser = path_is_file_or_std_stream)]
-    file: String,
+    /// Required unless `--book` is given.
+    #[arg(value_parser = path_is_file_or_std_stream, required_unless_present = "book")]
+    file: Option<String>,
+    /// Render a directory of chapters listed in its `SUMMARY.md` into a
+    /// linked HTML site instead of rendering a single file.
+    #[arg(long, value_parser = path_is_dir, conflicts_with = "file")]
+    book: Option<String>,
     /// An optional tera template to use for rendering.
     /// Additional values are added to the tera context for inheritance.
     #[arg(short = 'T', long, value_parser = path_is_file)]
     template: Vec<String>,
     /// Output file to write to. Defaults to <base_name(FILE)>.html.
     /// Use `-` to write to stdout instead.
+    /// In `--book` mode this is the output directory instead, defaulting to
+    /// the book directory itself.
     #[arg(short = 'O', long)]
     output: Option<String>,
     /// Explicitly set the title of the page.
@@ -40,8 +51,26 @@ struct Args {
     ///
     /// Example:
     /// pygmentize -f html -O cssclass=syntax -l {}
-    #[arg(long)]
+    #[arg(long, conflicts_with = "highlight_builtin")]
     highlight_command: Option<String>,
+    /// Highlight code blocks in-process with a built-in syntect highlighter
+    /// instead of shelling out to `--highlight-command`.
+    #[arg(long)]
+    highlight_builtin: bool,
+    /// Theme to use with `--highlight-builtin`.
+    #[arg(long, default_value = "InspiredGitHub")]
+    highlight_theme: String,
+    /// Write a JSON search index (a document store plus an inverted token
+    /// index) built from the rendered page(s) to this file, for offline
+    /// full-text search in the browser.
+    #[arg(long)]
+    search_index: Option<String>,
+    /// JSON file mapping shortcut/reference link names (e.g. `[Some Page]`)
+    /// to a URL, or to `{ "url": ..., "title": ... }`, looked up
+    /// case-insensitively. A document's own `links` frontmatter table is
+    /// merged in on top and takes precedence.
+    #[arg(long, value_parser = path_is_file)]
+    link_map: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -57,9 +86,38 @@ impl FromStr for Json {
 
 fn main() -> Result<()> {
     let args = Args::parse();
-    let markdown = read_input(&args.file)?;
 
-    let preprocessed = preprocess(create_markdown_parser(&markdown), args.highlight_command)?;
+    let highlighter = if args.highlight_builtin {
+        highlight::Highlighter::builtin(&args.highlight_theme)?
+    } else {
+        match args.highlight_command {
+            Some(command) => highlight::Highlighter::Command(command),
+            None => highlight::Highlighter::None,
+        }
+    };
+
+    if let Some(book_dir) = &args.book {
+        return book::render_book(
+            book_dir,
+            args.output,
+            args.metadata,
+            &args.template,
+            &highlighter,
+            args.search_index,
+            args.link_map,
+        );
+    }
+
+    let file = args.file.expect("required_unless_present = \"book\"");
+    let markdown = read_input(&file)?;
+    let output_file = output_file_name(&file, args.output);
+
+    let link_map = links::LinkMap::build(args.link_map.as_deref(), &markdown)?;
+    let mut resolve_link = |broken_link| link_map.resolve(broken_link);
+    let preprocessed = preprocess(
+        create_markdown_parser(&markdown, &mut resolve_link),
+        &highlighter,
+    )?;
     let title = args.title.or_else(|| {
         preprocessed
             .title_events
@@ -72,11 +130,17 @@ fn main() -> Result<()> {
         preprocessed.has_math,
         preprocessed.has_highlighted_code,
         &preprocessed.metadata,
+        &preprocessed.toc,
         &body_html,
         &args.template,
+        None,
     )?
     .unwrap_or(body_html);
-    write_output(&output_file_name(&args.file, args.output), &html)?;
+    write_output(&output_file, &html)?;
+
+    if let Some(search_index) = &args.search_index {
+        search::write_index(search_index, &[(output_file, preprocessed.sections)])?;
+    }
     Ok(())
 }
 
@@ -92,8 +156,10 @@ fn render_template(
     math: bool,
     has_highlighted_code: bool,
     frontmatter: &Option<json::Value>,
+    toc: &[TocEntry],
     content: &str,
     template_files: &[String],
+    book_nav: Option<&book::BookNav>,
 ) -> Result<Option<String>> {
     let mut tera = tera::Tera::default();
     tera.add_template_files(template_files.iter().map(|f| (f, None::<&str>)))?;
@@ -106,6 +172,13 @@ fn render_template(
     }
     context.insert("math", &math);
     context.insert("has_highlighted_code", &has_highlighted_code);
+    context.insert("toc", toc);
+    if let Some(book_nav) = book_nav {
+        context.insert("path", &book_nav.path);
+        context.insert("prev", &book_nav.prev);
+        context.insert("next", &book_nav.next);
+        context.insert("chapters", &book_nav.chapters);
+    }
 
     if let Some(template) = template_files.first() {
         Ok(Some(tera.render(template, &context)?))
@@ -140,6 +213,14 @@ fn path_is_file(path: &str) -> Result<String, String> {
     }
 }
 
+fn path_is_dir(path: &str) -> Result<String, String> {
+    if Path::new(path).is_dir() {
+        Ok(path.to_owned())
+    } else {
+        Err(format!("'{path}' does not exist or is not a directory."))
+    }
+}
+
 fn path_is_file_or_std_stream(path: &str) -> Result<String, String> {
     if path == "-" {
         Ok(path.to_owned())
@@ -158,7 +239,7 @@ fn read_input(path: &str) -> io::Result<String> {
     }
 }
 
-fn to_plain_text<'a>(events: impl Iterator<Item = pulldown_cmark::Event<'a>>) -> String {
+pub(crate) fn to_plain_text<'a>(events: impl Iterator<Item = pulldown_cmark::Event<'a>>) -> String {
     events
         .filter_map(|e| {
             if let pulldown_cmark::Event::Text(t) = e {
@@ -170,7 +251,12 @@ fn to_plain_text<'a>(events: impl Iterator<Item = pulldown_cmark::Event<'a>>) ->
         .join("")
 }
 
-fn create_markdown_parser(markdown: &str) -> pulldown_cmark::Parser {
+fn create_markdown_parser<'a, 'b>(
+    markdown: &'a str,
+    resolve_link: &'b mut impl FnMut(
+        pulldown_cmark::BrokenLink<'a>,
+    ) -> Option<(pulldown_cmark::CowStr<'a>, pulldown_cmark::CowStr<'a>)>,
+) -> pulldown_cmark::Parser<'a, 'b> {
     use pulldown_cmark::{Options, Parser};
     let options = Options::ENABLE_STRIKETHROUGH
         | Options::ENABLE_FOOTNOTES
@@ -179,5 +265,5 @@ fn create_markdown_parser(markdown: &str) -> pulldown_cmark::Parser {
         | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
         | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS
         | Options::ENABLE_MATH;
-    Parser::new_ext(markdown, options)
+    Parser::new_with_broken_link_callback(markdown, options, Some(resolve_link))
 }
@@ -1,13 +1,15 @@
-use crate::highlight::highlight_code;
+use crate::highlight::Highlighter;
+use crate::to_plain_text;
 use anyhow::{Context, Result};
 use pulldown_cmark::{
     CowStr,
     Event::{self, *},
-    HeadingLevel::*,
+    HeadingLevel::{self, *},
     MetadataBlockKind,
     Tag::*,
     TagEnd,
 };
+use serde::Serialize;
 use std::collections::HashMap;
 use std::mem;
 
@@ -17,11 +19,30 @@ pub(crate) struct PreprocessedMarkdown<'a> {
     pub(crate) has_math: bool,
     pub(crate) has_highlighted_code: bool,
     pub(crate) metadata: Option<json::Value>,
+    pub(crate) toc: Vec<TocEntry>,
+    pub(crate) sections: Vec<Section>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TocEntry {
+    pub(crate) name: String,
+    pub(crate) id: String,
+    pub(crate) children: Vec<TocEntry>,
+}
+
+/// The content of one document split at heading boundaries, for search
+/// indexing. `body` covers everything up to (but not including) the next
+/// heading of any level.
+pub(crate) struct Section {
+    pub(crate) id: String,
+    pub(crate) title: String,
+    pub(crate) breadcrumbs: Vec<String>,
+    pub(crate) body: String,
 }
 
 pub(crate) fn preprocess<'a>(
     parser: impl Iterator<Item = Event<'a>>,
-    highlight_command: Option<String>,
+    highlighter: &Highlighter,
 ) -> Result<PreprocessedMarkdown<'a>> {
     let mut events = Vec::new();
     let mut title_events = None;
@@ -30,6 +51,11 @@ pub(crate) fn preprocess<'a>(
     let mut has_highlighted_code = false;
     let mut numbers = HashMap::new();
     let mut metadata = None;
+    let mut heading_ids = HashMap::new();
+    let mut toc_stack: Vec<(HeadingLevel, TocEntry)> = Vec::new();
+    let mut toc = Vec::new();
+    let mut sections = Vec::new();
+    let mut current_section: Option<SectionBuilder> = None;
 
     let mut state = State::default();
     for event in parser {
@@ -43,11 +69,10 @@ pub(crate) fn preprocess<'a>(
         }
 
         state = match (mem::take(&mut state), event) {
-            (State::Default, e @ Start(Heading { level: H1, .. })) if title_events.is_none() => {
-                title_events = Some(Vec::new());
-                events.push(e);
-                State::Title
-            }
+            (State::Default, Start(Heading { level, .. })) => State::Heading {
+                level,
+                buffer: Vec::new(),
+            },
             (State::Default, ref e @ Start(FootnoteDefinition(ref label))) => {
                 State::FootnoteDefinition(label.clone(), vec![e.clone()])
             }
@@ -55,7 +80,7 @@ pub(crate) fn preprocess<'a>(
                 State::TomlMetadata(String::new())
             }
             (State::Default, Start(CodeBlock(pulldown_cmark::CodeBlockKind::Fenced(tag))))
-                if !tag.is_empty() && highlight_command.is_some() =>
+                if !tag.is_empty() && highlighter.is_enabled() =>
             {
                 State::FencedCodeBlock {
                     code: String::new(),
@@ -63,19 +88,47 @@ pub(crate) fn preprocess<'a>(
                 }
             }
             (state @ State::Default, e) => {
+                if let Some(section) = &mut current_section {
+                    section.events.push(e.clone());
+                }
                 events.push(e);
                 state
             }
-            (State::Title, e @ End(TagEnd::Heading(H1))) => {
-                events.push(e);
+            (State::Heading { level, buffer }, End(TagEnd::Heading(_))) => {
+                if let Some(section) = current_section.take() {
+                    sections.push(section.finish());
+                }
+
+                let text = to_plain_text(buffer.iter().cloned());
+                let id = unique_id(&slugify(&text), &mut heading_ids);
+
+                if title_events.is_none() && level == H1 {
+                    title_events = Some(buffer.clone());
+                }
+
+                let mut inner_html = String::new();
+                pulldown_cmark::html::push_html(&mut inner_html, buffer.into_iter());
+                let tag = heading_tag(level);
+                events.extend([
+                    Event::Start(HtmlBlock),
+                    Event::Html(format!("<{tag} id=\"{id}\">{inner_html}</{tag}>\n").into()),
+                    Event::End(TagEnd::HtmlBlock),
+                ]);
+
+                pop_shallower_toc_entries(&mut toc_stack, &mut toc, level);
+                let breadcrumbs = toc_stack.iter().map(|(_, e)| e.name.clone()).collect();
+                current_section = Some(SectionBuilder {
+                    id: id.clone(),
+                    title: text.clone(),
+                    breadcrumbs,
+                    events: Vec::new(),
+                });
+                toc_stack.push((level, TocEntry { name: text, id, children: Vec::new() }));
                 State::Default
             }
-            (state @ State::Title, e) => {
-                if let Some(title_events) = &mut title_events {
-                    title_events.push(e.clone());
-                }
-                events.push(e);
-                state
+            (State::Heading { level, mut buffer }, e) => {
+                buffer.push(e);
+                State::Heading { level, buffer }
             }
             (State::FootnoteDefinition(label, mut events), e @ End(TagEnd::FootnoteDefinition)) => {
                 events.push(e);
@@ -109,12 +162,12 @@ pub(crate) fn preprocess<'a>(
             }
             (State::FencedCodeBlock { code, tag }, End(TagEnd::CodeBlock)) => {
                 has_highlighted_code = true;
-                let command = highlight_command
-                    .as_deref()
-                    .unwrap_or_else(|| unreachable!());
+                if let Some(section) = &mut current_section {
+                    section.events.push(Event::Text(code.clone().into()));
+                }
                 events.extend([
                     Event::Start(HtmlBlock),
-                    Event::Html(highlight_code(command, tag, code)?.into()),
+                    Event::Html(highlighter.highlight(tag, code)?.into()),
                     Event::End(TagEnd::HtmlBlock),
                 ]);
                 State::Default
@@ -133,20 +186,120 @@ pub(crate) fn preprocess<'a>(
             .flat_map(|(_, events)| events),
     );
 
+    if let Some(section) = current_section.take() {
+        sections.push(section.finish());
+    }
+    flush_toc_stack(&mut toc_stack, &mut toc);
+
     Ok(PreprocessedMarkdown {
         events,
         title_events,
         has_math,
         has_highlighted_code,
         metadata,
+        toc,
+        sections,
     })
 }
 
+struct SectionBuilder<'a> {
+    id: String,
+    title: String,
+    breadcrumbs: Vec<String>,
+    events: Vec<Event<'a>>,
+}
+
+impl<'a> SectionBuilder<'a> {
+    fn finish(self) -> Section {
+        Section {
+            id: self.id,
+            title: self.title,
+            breadcrumbs: self.breadcrumbs,
+            body: to_plain_text(self.events.into_iter()),
+        }
+    }
+}
+
+fn heading_tag(level: HeadingLevel) -> &'static str {
+    match level {
+        H1 => "h1",
+        H2 => "h2",
+        H3 => "h3",
+        H4 => "h4",
+        H5 => "h5",
+        H6 => "h6",
+    }
+}
+
+// Lowercases, collapses runs of non-alphanumeric characters into a single
+// `-`, and trims leading/trailing `-`. Falls back to "section" for a
+// heading with no alphanumeric characters at all, so it never produces an
+// empty id.
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    let mut last_was_dash = false;
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+            last_was_dash = false;
+        } else if !last_was_dash {
+            slug.push('-');
+            last_was_dash = true;
+        }
+    }
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        "section".to_owned()
+    } else {
+        slug.to_owned()
+    }
+}
+
+fn unique_id(base: &str, seen: &mut HashMap<String, usize>) -> String {
+    match seen.get_mut(base) {
+        None => {
+            seen.insert(base.to_owned(), 0);
+            base.to_owned()
+        }
+        Some(count) => {
+            *count += 1;
+            format!("{base}-{count}")
+        }
+    }
+}
+
+fn pop_shallower_toc_entries(
+    stack: &mut Vec<(HeadingLevel, TocEntry)>,
+    roots: &mut Vec<TocEntry>,
+    level: HeadingLevel,
+) {
+    while matches!(stack.last(), Some((top_level, _)) if *top_level >= level) {
+        let (_, popped) = stack.pop().unwrap();
+        attach_toc_entry(stack, roots, popped);
+    }
+}
+
+fn attach_toc_entry(stack: &mut [(HeadingLevel, TocEntry)], roots: &mut Vec<TocEntry>, entry: TocEntry) {
+    match stack.last_mut() {
+        Some((_, parent)) => parent.children.push(entry),
+        None => roots.push(entry),
+    }
+}
+
+fn flush_toc_stack(stack: &mut Vec<(HeadingLevel, TocEntry)>, roots: &mut Vec<TocEntry>) {
+    while let Some((_, entry)) = stack.pop() {
+        attach_toc_entry(stack, roots, entry);
+    }
+}
+
 #[derive(Default, Clone)]
 enum State<'a> {
     #[default]
     Default,
-    Title,
+    Heading {
+        level: HeadingLevel,
+        buffer: Vec<Event<'a>>,
+    },
     FootnoteDefinition(CowStr<'a>, Vec<Event<'a>>),
     TomlMetadata(String),
     FencedCodeBlock {
@@ -154,3 +307,100 @@ enum State<'a> {
         tag: CowStr<'a>,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlight::Highlighter;
+    use pulldown_cmark::Parser;
+
+    fn preprocess_str(markdown: &str) -> PreprocessedMarkdown {
+        preprocess(Parser::new(markdown), &Highlighter::None).unwrap()
+    }
+
+    #[test]
+    fn slugify_lowercases_and_collapses_punctuation() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  --Leading/Trailing--  "), "leading-trailing");
+        assert_eq!(slugify("Already-Slugged"), "already-slugged");
+    }
+
+    #[test]
+    fn slugify_falls_back_to_section_when_nothing_alphanumeric_remains() {
+        assert_eq!(slugify("---"), "section");
+        assert_eq!(slugify("!!!"), "section");
+    }
+
+    #[test]
+    fn unique_id_suffixes_repeats() {
+        let mut seen = HashMap::new();
+        assert_eq!(unique_id("intro", &mut seen), "intro");
+        assert_eq!(unique_id("intro", &mut seen), "intro-1");
+        assert_eq!(unique_id("intro", &mut seen), "intro-2");
+        assert_eq!(unique_id("other", &mut seen), "other");
+    }
+
+    #[test]
+    fn toc_nests_headings_by_level() {
+        let markdown = "\
+# Title
+
+## Section A
+
+### Subsection A.1
+
+## Section B
+";
+        let toc = preprocess_str(markdown).toc;
+        assert_eq!(toc.len(), 1);
+        assert_eq!(toc[0].name, "Title");
+        assert_eq!(toc[0].children.len(), 2);
+        assert_eq!(toc[0].children[0].name, "Section A");
+        assert_eq!(toc[0].children[0].children.len(), 1);
+        assert_eq!(toc[0].children[0].children[0].name, "Subsection A.1");
+        assert_eq!(toc[0].children[1].name, "Section B");
+        assert!(toc[0].children[1].children.is_empty());
+    }
+
+    #[test]
+    fn toc_pops_back_to_a_shallower_sibling_level() {
+        // A level-3 heading directly followed by another level-2 heading
+        // must pop the level-3 entry off the stack before attaching the
+        // new level-2 entry as a sibling of the first, not a child of it.
+        let markdown = "\
+# Title
+
+## Section A
+
+### Subsection A.1
+
+### Subsection A.2
+
+## Section B
+";
+        let toc = preprocess_str(markdown).toc;
+        assert_eq!(toc[0].children[0].children.len(), 2);
+        assert_eq!(toc[0].children[0].children[1].name, "Subsection A.2");
+        assert_eq!(toc[0].children.len(), 2);
+        assert_eq!(toc[0].children[1].name, "Section B");
+    }
+
+    #[test]
+    fn heading_ids_are_unique_and_sections_break_on_headings() {
+        let markdown = "\
+# Intro
+
+first paragraph
+
+# Intro
+
+second paragraph
+";
+        let result = preprocess_str(markdown);
+        assert_eq!(result.toc[0].id, "intro");
+        assert_eq!(result.toc[1].id, "intro-1");
+        assert_eq!(result.sections.len(), 2);
+        assert_eq!(result.sections[0].body.trim(), "first paragraph");
+        assert_eq!(result.sections[1].body.trim(), "second paragraph");
+    }
+}
@@ -0,0 +1,123 @@
+use crate::preprocess::Section;
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+
+/// One document record in the search index: a heading-anchored section of a
+/// rendered page, addressable by `id` (the page path plus the heading's
+/// anchor) and searchable by its `title` and `body` text.
+#[derive(Serialize)]
+struct Record {
+    id: String,
+    title: String,
+    breadcrumbs: Vec<String>,
+    body: String,
+}
+
+/// One of a token's postings: a document (an index into `documents`) it
+/// appears in, and how often.
+#[derive(Serialize)]
+struct Posting {
+    doc: usize,
+    term_frequency: usize,
+}
+
+#[derive(Serialize)]
+struct SearchIndex {
+    documents: Vec<Record>,
+    index: HashMap<String, Vec<Posting>>,
+}
+
+/// Writes a JSON search index built from each document's heading-anchored
+/// sections to `path`. `documents` pairs each page's output path with the
+/// sections `preprocess` split it into.
+pub(crate) fn write_index(path: &str, documents: &[(String, Vec<Section>)]) -> Result<()> {
+    let records: Vec<Record> = documents
+        .iter()
+        .flat_map(|(doc_path, sections)| {
+            sections.iter().map(move |section| Record {
+                id: format!("{doc_path}#{}", section.id),
+                title: section.title.clone(),
+                breadcrumbs: section.breadcrumbs.clone(),
+                body: section.body.clone(),
+            })
+        })
+        .collect();
+
+    let mut index: HashMap<String, Vec<Posting>> = HashMap::new();
+    for (doc, record) in records.iter().enumerate() {
+        let mut term_frequencies: HashMap<String, usize> = HashMap::new();
+        for token in tokenize(&record.title).chain(tokenize(&record.body)) {
+            *term_frequencies.entry(token).or_insert(0) += 1;
+        }
+        for (token, term_frequency) in term_frequencies {
+            index.entry(token).or_default().push(Posting { doc, term_frequency });
+        }
+    }
+
+    let search_index = SearchIndex { documents: records, index };
+    fs::write(path, json::to_string(&search_index)?)?;
+    Ok(())
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_splits_on_punctuation_and_lowercases() {
+        let tokens: Vec<_> = tokenize("Hello, World! It's search-indexed.").collect();
+        assert_eq!(tokens, vec!["hello", "world", "it", "s", "search", "indexed"]);
+    }
+
+    #[test]
+    fn tokenize_skips_empty_runs() {
+        let tokens: Vec<_> = tokenize("  one   two  ").collect();
+        assert_eq!(tokens, vec!["one", "two"]);
+    }
+
+    fn section(id: &str, title: &str, body: &str) -> Section {
+        Section {
+            id: id.to_owned(),
+            title: title.to_owned(),
+            breadcrumbs: Vec::new(),
+            body: body.to_owned(),
+        }
+    }
+
+    #[test]
+    fn write_index_builds_postings_with_term_frequency() {
+        let dir = std::env::temp_dir().join(format!("seite-search-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("index.json");
+
+        let documents = vec![
+            ("page-a.html".to_owned(), vec![section("intro", "Intro", "apple apple banana")]),
+            ("page-b.html".to_owned(), vec![section("intro", "Intro", "banana cherry")]),
+        ];
+        write_index(path.to_str().unwrap(), &documents).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        let parsed: json::Value = json::from_str(&written).unwrap();
+
+        assert_eq!(parsed["documents"][0]["id"], "page-a.html#intro");
+        assert_eq!(parsed["documents"][1]["id"], "page-b.html#intro");
+
+        let apple_postings = parsed["index"]["apple"].as_array().unwrap();
+        assert_eq!(apple_postings.len(), 1);
+        assert_eq!(apple_postings[0]["doc"], 0);
+        assert_eq!(apple_postings[0]["term_frequency"], 2);
+
+        let banana_postings = parsed["index"]["banana"].as_array().unwrap();
+        assert_eq!(banana_postings.len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}